@@ -0,0 +1,234 @@
+use std::io::{self, Write};
+use debcrafter::{ConfFormat, ConfType, HiddenVarVal, PackageInstance, VarType};
+use debcrafter::confpath::{self, ConfValue};
+use debcrafter::postinst::{self, LeafSource};
+use crate::codegen::LazyCreateBuilder;
+
+/// Generates the postinst shell snippet that reads each `Dynamic` config's
+/// `ivars`/`evars`/`hvars` out of debconf (or, for `hvars`, out of their
+/// constant/script value) and assembles the on-disk config file in the
+/// format declared by the spec.
+pub fn generate(instance: &PackageInstance, out: LazyCreateBuilder) -> io::Result<()> {
+    let mut out = out.finalize();
+
+    for (conf_name, config) in instance.effective_config() {
+        if let ConfType::Dynamic { format, ivars, evars, hvars, .. } = &config.conf_type {
+            out.separator("\n")?;
+            writeln!(out, "# {} ({})", conf_name, instance.name)?;
+
+            let mut entries = Vec::new();
+
+            for (var_name, var_spec) in ivars {
+                let template = format!("{}/{}", instance.name, var_name);
+                let shell_var = shell_ident(var_name);
+                let priority = postinst::priority_str(&var_spec.priority);
+
+                let mut script = String::new();
+                postinst::emit_default(&mut script, &template, var_spec);
+                postinst::emit_validated_input(&mut script, &priority, &template, &var_spec.ty, var_spec.constraint.as_ref());
+                write!(out, "{}", script)?;
+                writeln!(out, "{}=\"$value\"", shell_var)?;
+
+                entries.push((var_name.clone(), (LeafSource::Dynamic(shell_var), is_string_type(&var_spec.ty))));
+            }
+
+            for (pkg, vars) in evars {
+                for (var_name, ext) in vars {
+                    let remote_var = ext.name.as_deref().unwrap_or(var_name);
+                    let template = format!("{}/{}", pkg, remote_var);
+                    let shell_var = shell_ident(&format!("{}_{}", pkg, var_name));
+
+                    writeln!(out, "db_get {}", template)?;
+                    writeln!(out, "{}=\"$RET\"", shell_var)?;
+
+                    entries.push((var_name.clone(), (LeafSource::Dynamic(shell_var), true)));
+                }
+            }
+
+            for (var_name, var_spec) in hvars {
+                let is_string = is_string_type(&var_spec.ty);
+                match &var_spec.val {
+                    HiddenVarVal::Constant(value) => {
+                        entries.push((var_name.clone(), (LeafSource::Literal(value.clone()), is_string)));
+                    },
+                    HiddenVarVal::Script(script) => {
+                        let shell_var = shell_ident(var_name);
+                        writeln!(out, "{}=\"$({})\"", shell_var, script)?;
+                        entries.push((var_name.clone(), (LeafSource::Dynamic(shell_var), is_string)));
+                    },
+                }
+            }
+
+            let tree = confpath::build_tree(entries)
+                .unwrap_or_else(|err| panic!("Invalid variable path in {}/{}: {}", instance.name, conf_name, err));
+
+            let mut body = String::new();
+            render_tree(&mut body, format.clone(), &tree);
+            write!(out, "{}", body)?;
+        }
+    }
+    Ok(())
+}
+
+/// Turns an arbitrary variable/path segment into a valid shell identifier.
+fn shell_ident(name: &str) -> String {
+    let sanitized: String = name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    format!("V_{}", sanitized)
+}
+
+fn is_string_type(ty: &VarType) -> bool {
+    !matches!(ty, VarType::Bool | VarType::Uint | VarType::BindPort)
+}
+
+type Tree = ConfValue<(LeafSource, bool)>;
+
+/// Renders the tree built by `confpath::build_tree` into `out`, nesting
+/// tables/arrays the way `format` actually expects (JSON objects/arrays,
+/// YAML block mappings/sequences, TOML `[section]`/`[[section]]` headers)
+/// instead of rejoining each leaf's path into one flat key.
+fn render_tree(out: &mut String, format: ConfFormat, tree: &Tree) {
+    match format {
+        ConfFormat::Plain => render_plain(out, tree),
+        ConfFormat::Toml => render_toml(out, tree, &mut Vec::new(), false),
+        ConfFormat::Json => render_json(out, tree, 0, None, true),
+        ConfFormat::Yaml => render_yaml(out, tree, 0),
+    }
+}
+
+/// `Plain` has no key/value structure - every leaf is written on its own
+/// line in tree order, regardless of nesting.
+fn render_plain(out: &mut String, node: &Tree) {
+    match node {
+        ConfValue::Leaf((source, _)) => postinst::emit_plain_leaf(out, source),
+        ConfValue::Table(entries) => entries.iter().for_each(|(_, child)| render_plain(out, child)),
+        ConfValue::Array(items) => items.iter().for_each(|child| render_plain(out, child)),
+    }
+}
+
+/// Renders one TOML table. `path` is the dotted path of `node` from the
+/// document root; a table's own scalar keys are written directly under its
+/// `[path]`/`[[path]]` header (inline arrays of scalars included), before
+/// any subtable headers, since TOML requires simple keys to precede the
+/// next table header.
+fn render_toml(out: &mut String, node: &Tree, path: &mut Vec<String>, is_array_element: bool) {
+    let entries = match node {
+        ConfValue::Table(entries) => entries,
+        _ => panic!("confpath::build_tree produced a non-table at a table position"),
+    };
+
+    if !path.is_empty() {
+        postinst::emit_toml_header(out, &path.join("."), is_array_element);
+    }
+
+    let is_table_array = |items: &Vec<Tree>| items.iter().all(|item| matches!(item, ConfValue::Table(_)));
+
+    for (key, child) in entries {
+        match child {
+            ConfValue::Leaf((source, is_string)) => postinst::emit_toml_entry(out, key, source, *is_string),
+            ConfValue::Array(items) if !is_table_array(items) => {
+                let leaves: Vec<(LeafSource, bool)> = items.iter().map(|item| match item {
+                    ConfValue::Leaf((source, is_string)) => (source.clone(), *is_string),
+                    _ => panic!("TOML config output doesn't support arrays mixing tables and scalars (key {:?})", key),
+                }).collect();
+                postinst::emit_toml_inline_array(out, key, &leaves);
+            },
+            _ => {},
+        }
+    }
+
+    for (key, child) in entries {
+        match child {
+            ConfValue::Table(_) => {
+                path.push(key.clone());
+                render_toml(out, child, path, false);
+                path.pop();
+            },
+            ConfValue::Array(items) if is_table_array(items) => {
+                path.push(key.clone());
+                for item in items {
+                    render_toml(out, item, path, true);
+                }
+                path.pop();
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Renders one JSON value. `key` names this value within its parent object
+/// (`None` for the root or for array elements); `last` controls whether a
+/// trailing comma is written after it.
+fn render_json(out: &mut String, node: &Tree, indent: usize, key: Option<&str>, last: bool) {
+    match node {
+        ConfValue::Table(entries) => {
+            postinst::emit_json_open(out, indent, key, false);
+            let count = entries.len();
+            for (i, (k, child)) in entries.iter().enumerate() {
+                let is_last = i + 1 == count;
+                match child {
+                    ConfValue::Leaf((source, is_string)) => postinst::emit_json_entry(out, indent + 1, k, source, *is_string, is_last),
+                    _ => render_json(out, child, indent + 1, Some(k), is_last),
+                }
+            }
+            postinst::emit_json_close(out, indent, false, last);
+        },
+        ConfValue::Array(items) => {
+            postinst::emit_json_open(out, indent, key, true);
+            let count = items.len();
+            for (i, item) in items.iter().enumerate() {
+                let is_last = i + 1 == count;
+                match item {
+                    ConfValue::Leaf((source, is_string)) => postinst::emit_json_item(out, indent + 1, source, *is_string, is_last),
+                    _ => render_json(out, item, indent + 1, None, is_last),
+                }
+            }
+            postinst::emit_json_close(out, indent, true, last);
+        },
+        ConfValue::Leaf(_) => unreachable!("leaves are rendered by the parent table/array before recursing"),
+    }
+}
+
+/// Renders one YAML mapping. Sequence-of-mapping items fold their first key
+/// onto the `- ` line and indent the rest to align under it, matching
+/// common YAML style. A sequence nested directly inside another sequence
+/// (from a path such as `matrix[1][2]`) is not supported and panics.
+fn render_yaml(out: &mut String, node: &Tree, indent: usize) {
+    match node {
+        ConfValue::Table(entries) => {
+            let pad = "  ".repeat(indent);
+            for (key, child) in entries {
+                match child {
+                    ConfValue::Leaf((source, is_string)) => postinst::emit_yaml_entry(out, &pad, key, source, *is_string),
+                    _ => {
+                        postinst::emit_yaml_header(out, &pad, key);
+                        render_yaml(out, child, indent + 1);
+                    },
+                }
+            }
+        },
+        ConfValue::Array(items) => {
+            let base_pad = "  ".repeat(indent);
+            for item in items {
+                match item {
+                    ConfValue::Leaf((source, is_string)) => {
+                        postinst::emit_yaml_item(out, &format!("{}- ", base_pad), source, *is_string);
+                    },
+                    ConfValue::Table(entries) => {
+                        for (i, (key, child)) in entries.iter().enumerate() {
+                            let pad = if i == 0 { format!("{}- ", base_pad) } else { format!("{}  ", base_pad) };
+                            match child {
+                                ConfValue::Leaf((source, is_string)) => postinst::emit_yaml_entry(out, &pad, key, source, *is_string),
+                                _ => {
+                                    postinst::emit_yaml_header(out, &pad, key);
+                                    render_yaml(out, child, indent + 2);
+                                },
+                            }
+                        }
+                    },
+                    ConfValue::Array(_) => panic!("YAML config output doesn't support a sequence nested directly inside another sequence"),
+                }
+            }
+        },
+        ConfValue::Leaf(_) => unreachable!("the tree root is always a table"),
+    }
+}
@@ -1,24 +1,30 @@
 use std::io::{self, Write};
-use debcrafter::{PackageInstance, PackageConfig, ConfType, VarType};
+use debcrafter::{PackageInstance, ConfType, VarType};
 use crate::codegen::{LazyCreateBuilder};
 
 pub fn generate(instance: &PackageInstance, out: LazyCreateBuilder) -> io::Result<()> {
     let mut out = out.finalize();
 
-    for (_, config) in instance.config() {
+    for (_, config) in instance.effective_config() {
         if let ConfType::Dynamic { ivars, .. } = &config.conf_type {
             for (var, var_spec) in ivars {
                 out.separator("\n")?;
 
                 writeln!(out, "Template: {}/{}", instance.name, var)?;
 
-                let template_type = if let VarType::Bool = var_spec.ty {
-                    "bool"
-                } else {
-                    "string"
+                let template_type = match &var_spec.ty {
+                    VarType::Bool => "bool",
+                    VarType::Enum { multi: true, .. } => "multiselect",
+                    VarType::Enum { multi: false, .. } => "select",
+                    _ => "string",
                 };
                 writeln!(out, "Type: {}", template_type)?;
 
+                if let VarType::Enum { choices, .. } = &var_spec.ty {
+                    let choices = choices.iter().map(|choice| choice.replace(',', "\\,")).collect::<Vec<_>>().join(", ");
+                    writeln!(out, "Choices: {}", choices)?;
+                }
+
                 if let Some(default) = &var_spec.default {
                     writeln!(out, "Default: {}", default)?;
                 }
@@ -0,0 +1,240 @@
+//! Splits dotted/bracketed variable keys (`server.tls.port`,
+//! `peers[0].host`) and folds them into a tree for the config codegen to
+//! render as nested tables (TOML) or objects (JSON/YAML).
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+#[derive(Debug)]
+pub enum PathError {
+    EmptySegment(String),
+    InvalidIndex(String),
+    Conflict { path: String, expected: &'static str, },
+}
+
+impl fmt::Display for PathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathError::EmptySegment(path) => write!(f, "empty segment in variable path {:?}", path),
+            PathError::InvalidIndex(path) => write!(f, "invalid array index in variable path {:?}", path),
+            PathError::Conflict { path, expected } => write!(f, "variable path {:?} is used both as a {} and something else", path, expected),
+        }
+    }
+}
+
+/// Splits `path` on `.`, further splitting each dotted component on any
+/// number of trailing `[N]` index suffixes (`peers[0].host` ->
+/// `[Key("peers"), Index(0), Key("host")]`).
+pub fn parse_path(path: &str) -> Result<Vec<Segment>, PathError> {
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        if part.is_empty() {
+            return Err(PathError::EmptySegment(path.to_owned()));
+        }
+
+        let key_end = part.find('[').unwrap_or(part.len());
+        let (key, mut indices) = part.split_at(key_end);
+        if key.is_empty() {
+            return Err(PathError::EmptySegment(path.to_owned()));
+        }
+        segments.push(Segment::Key(key.to_owned()));
+
+        while !indices.is_empty() {
+            if !indices.starts_with('[') {
+                return Err(PathError::InvalidIndex(path.to_owned()));
+            }
+            let close = indices.find(']').ok_or_else(|| PathError::InvalidIndex(path.to_owned()))?;
+            let index = indices[1..close].parse::<usize>().map_err(|_| PathError::InvalidIndex(path.to_owned()))?;
+            segments.push(Segment::Index(index));
+            indices = &indices[close + 1..];
+        }
+    }
+    Ok(segments)
+}
+
+/// A node in the folded config tree. Generic over the leaf type so both the
+/// static literal values and the shell-variable placeholders used by the
+/// postinst codegen can share the same tree shape.
+#[derive(Debug, Clone)]
+pub enum ConfValue<L> {
+    Leaf(L),
+    Table(Vec<(String, ConfValue<L>)>),
+    Array(Vec<ConfValue<L>>),
+}
+
+impl<L> ConfValue<L> {
+    fn empty_for(segment: &Segment) -> Self {
+        match segment {
+            Segment::Key(_) => ConfValue::Table(Vec::new()),
+            Segment::Index(_) => ConfValue::Array(Vec::new()),
+        }
+    }
+}
+
+/// Inserts `value` at `path` (already parsed into `segments`) into `root`,
+/// creating intermediate tables/arrays as needed and merging them with any
+/// siblings already present. Fails if a path segment was already used as a
+/// different node kind (e.g. `foo` is both a table and a leaf, or both a
+/// table and an array).
+pub fn insert<L>(root: &mut ConfValue<L>, path: &str, segments: &[Segment], value: L) -> Result<(), PathError> {
+    match segments.split_first() {
+        None => {
+            match root {
+                ConfValue::Table(entries) if !entries.is_empty() => return Err(PathError::Conflict { path: path.to_owned(), expected: "leaf" }),
+                ConfValue::Array(items) if !items.is_empty() => return Err(PathError::Conflict { path: path.to_owned(), expected: "leaf" }),
+                _ => {},
+            }
+            *root = ConfValue::Leaf(value);
+            Ok(())
+        },
+        Some((Segment::Key(key), rest)) => {
+            let entries = match root {
+                ConfValue::Table(entries) => entries,
+                ConfValue::Leaf(_) => return Err(PathError::Conflict { path: path.to_owned(), expected: "table" }),
+                ConfValue::Array(_) => return Err(PathError::Conflict { path: path.to_owned(), expected: "table" }),
+            };
+            let child = match entries.iter_mut().find(|(k, _)| k == key) {
+                Some((_, child)) => child,
+                None => {
+                    let placeholder = rest.first().map(ConfValue::empty_for).unwrap_or(ConfValue::Table(Vec::new()));
+                    entries.push((key.clone(), placeholder));
+                    &mut entries.last_mut().unwrap().1
+                },
+            };
+            insert(child, path, rest, value)
+        },
+        Some((Segment::Index(index), rest)) => {
+            let items = match root {
+                ConfValue::Array(items) => items,
+                ConfValue::Leaf(_) => return Err(PathError::Conflict { path: path.to_owned(), expected: "array" }),
+                ConfValue::Table(_) => return Err(PathError::Conflict { path: path.to_owned(), expected: "array" }),
+            };
+            while items.len() <= *index {
+                let placeholder = rest.first().map(ConfValue::empty_for).unwrap_or(ConfValue::Table(Vec::new()));
+                items.push(placeholder);
+            }
+            insert(&mut items[*index], path, rest, value)
+        },
+    }
+}
+
+/// Builds a tree out of a flat list of `(dotted_path, value)` pairs,
+/// coalescing all paths that share a prefix into the same nested
+/// table/array.
+pub fn build_tree<L>(entries: impl IntoIterator<Item = (String, L)>) -> Result<ConfValue<L>, PathError> {
+    let mut root = ConfValue::Table(Vec::new());
+    for (path, value) in entries {
+        let segments = parse_path(&path)?;
+        insert(&mut root, &path, &segments, value)?;
+    }
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(entries: &ConfValue<i32>) -> &[(String, ConfValue<i32>)] {
+        match entries {
+            ConfValue::Table(entries) => entries,
+            _ => panic!("expected a table"),
+        }
+    }
+
+    #[test]
+    fn parse_path_splits_dots_and_indices() {
+        assert_eq!(parse_path("server.tls.port").unwrap(), vec![
+            Segment::Key("server".to_owned()),
+            Segment::Key("tls".to_owned()),
+            Segment::Key("port".to_owned()),
+        ]);
+        assert_eq!(parse_path("peers[0].host").unwrap(), vec![
+            Segment::Key("peers".to_owned()),
+            Segment::Index(0),
+            Segment::Key("host".to_owned()),
+        ]);
+        assert_eq!(parse_path("matrix[1][2]").unwrap(), vec![
+            Segment::Key("matrix".to_owned()),
+            Segment::Index(1),
+            Segment::Index(2),
+        ]);
+    }
+
+    #[test]
+    fn parse_path_rejects_empty_segments() {
+        for path in [".foo", "foo.", "foo..bar", ""] {
+            assert!(matches!(parse_path(path), Err(PathError::EmptySegment(_))), "{:?} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn parse_path_rejects_invalid_indices() {
+        for path in ["peers[abc]", "peers[0", "peers[]", "peers[0]extra"] {
+            assert!(matches!(parse_path(path), Err(PathError::InvalidIndex(_))), "{:?} should be rejected", path);
+        }
+    }
+
+    #[test]
+    fn build_tree_merges_shared_prefixes() {
+        let tree = build_tree(vec![
+            ("server.tls.port".to_owned(), 1),
+            ("server.tls.cert".to_owned(), 2),
+            ("server.timeout".to_owned(), 3),
+        ]).unwrap();
+
+        let server = table(&tree);
+        assert_eq!(server.len(), 1);
+        assert_eq!(server[0].0, "server");
+
+        let server_entries = table(&server[0].1);
+        assert_eq!(server_entries.len(), 2);
+        assert_eq!(server_entries[0].0, "tls");
+        assert_eq!(server_entries[1].0, "timeout");
+
+        let tls_entries = table(&server_entries[0].1);
+        assert_eq!(tls_entries.len(), 2);
+        assert!(matches!(tls_entries[0].1, ConfValue::Leaf(1)));
+        assert!(matches!(tls_entries[1].1, ConfValue::Leaf(2)));
+    }
+
+    #[test]
+    fn build_tree_folds_indexed_paths_into_one_array() {
+        let tree = build_tree(vec![
+            ("peers[0].host".to_owned(), 1),
+            ("peers[1].host".to_owned(), 2),
+        ]).unwrap();
+
+        let root = table(&tree);
+        assert_eq!(root.len(), 1);
+        match &root[0].1 {
+            ConfValue::Array(items) => {
+                assert_eq!(items.len(), 2);
+                for item in items {
+                    assert_eq!(table(item).len(), 1);
+                }
+            },
+            _ => panic!("expected peers to fold into one array"),
+        }
+    }
+
+    #[test]
+    fn insert_rejects_key_array_collisions() {
+        let mut root = ConfValue::Table(Vec::new());
+        insert(&mut root, "peers[0]", &parse_path("peers[0]").unwrap(), 1).unwrap();
+        let err = insert(&mut root, "peers.host", &parse_path("peers.host").unwrap(), 2).unwrap_err();
+        assert!(matches!(err, PathError::Conflict { expected: "table", .. }));
+    }
+
+    #[test]
+    fn insert_rejects_leaf_table_collisions() {
+        let mut root = ConfValue::Table(Vec::new());
+        insert(&mut root, "port", &parse_path("port").unwrap(), 1).unwrap();
+        let err = insert(&mut root, "port.extra", &parse_path("port.extra").unwrap(), 2).unwrap_err();
+        assert!(matches!(err, PathError::Conflict { expected: "table", .. }));
+    }
+}
@@ -5,6 +5,7 @@ use std::path::Path;
 use std::borrow::Cow;
 
 pub mod postinst;
+pub mod confpath;
 
 fn create_true() -> bool {
     true
@@ -61,7 +62,19 @@ pub fn load_file<T: for<'a> serde::Deserialize<'a>, P: AsRef<Path>>(file: P) ->
 
 impl Package {
     pub fn load<P: AsRef<Path>>(file: P) -> Self {
-        load_file(file)
+        let package: Self = load_file(file);
+        package.validate();
+        package
+    }
+
+    fn validate(&self) {
+        for (conf_name, conf) in self.config() {
+            if let ConfType::Dynamic { ivars, .. } = &conf.conf_type {
+                for (var_name, var) in ivars {
+                    var.validate(&self.name, conf_name, var_name);
+                }
+            }
+        }
     }
 
     pub fn load_includes<P: AsRef<Path>>(&self, dir: P) -> HashMap<String, Package> {
@@ -175,6 +188,12 @@ pub struct ConfExtPackageSpec {
     pub long_doc: Option<String>,
     #[serde(default)]
     pub config: HashMap<String, Config>,
+    /// Where this layer sits among other `ConfExt` packages that `extend`
+    /// the same base. Layers are applied in ascending order, so a higher
+    /// `priority` wins when two layers (or a layer and the base) define the
+    /// same config key or variable.
+    #[serde(default)]
+    pub priority: i32,
 }
 
 #[derive(Deserialize)]
@@ -198,7 +217,7 @@ pub struct CreateUser {
     pub home: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct Config {
     #[serde(default)]
     pub public: bool,
@@ -206,7 +225,42 @@ pub struct Config {
     pub conf_type: ConfType,
 }
 
-#[derive(Deserialize)]
+impl Config {
+    /// Merges `layer` on top of `self`, last-layer-wins. `Dynamic` entries
+    /// are merged field-by-field (`ivars`/`evars`/`hvars`/`cat_files`
+    /// merged key-by-key, `format` replaced, `cat_dir`/`comment` replaced
+    /// only when the layer sets them); `public` always takes the layer's
+    /// value. Any other combination
+    /// (e.g. a layer switching the config from `Dynamic` to `Static`, or
+    /// vice versa) replaces the whole entry.
+    fn merge_from(&mut self, layer: &Config) {
+        self.public = layer.public;
+        match (&mut self.conf_type, &layer.conf_type) {
+            (
+                ConfType::Dynamic { format, ivars, evars, hvars, cat_dir, cat_files, comment },
+                ConfType::Dynamic { format: l_format, ivars: l_ivars, evars: l_evars, hvars: l_hvars, cat_dir: l_cat_dir, cat_files: l_cat_files, comment: l_comment },
+            ) => {
+                *format = l_format.clone();
+                ivars.extend(l_ivars.iter().map(|(name, var)| (name.clone(), var.clone())));
+                for (pkg, vars) in l_evars {
+                    evars.entry(pkg.clone()).or_insert_with(HashMap::new)
+                        .extend(vars.iter().map(|(name, var)| (name.clone(), var.clone())));
+                }
+                hvars.extend(l_hvars.iter().map(|(name, var)| (name.clone(), var.clone())));
+                if l_cat_dir.is_some() {
+                    *cat_dir = l_cat_dir.clone();
+                }
+                cat_files.extend(l_cat_files.iter().cloned());
+                if l_comment.is_some() {
+                    *comment = l_comment.clone();
+                }
+            },
+            _ => self.conf_type = layer.conf_type.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
 #[serde(untagged)]
 pub enum ConfType {
     Static { content: String, #[serde(default)] internal: bool, },
@@ -225,11 +279,13 @@ pub enum ConfType {
     },
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum ConfFormat {
     Plain,
     Toml,
+    Json,
+    Yaml,
 }
 
 impl fmt::Display for ConfFormat {
@@ -237,12 +293,14 @@ impl fmt::Display for ConfFormat {
         match self {
             ConfFormat::Plain => write!(f, "plain"),
             ConfFormat::Toml => write!(f, "toml"),
+            ConfFormat::Json => write!(f, "json"),
+            ConfFormat::Yaml => write!(f, "yaml"),
         }
     }
 }
 
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct InternalVar {
     #[serde(flatten)]
     pub ty: VarType,
@@ -251,10 +309,57 @@ pub struct InternalVar {
     pub long_doc: Option<String>,
     #[serde(default)]
     pub default: Option<String>,
+    #[serde(default)]
+    pub default_from_env: Option<String>,
+    #[serde(default)]
+    pub env_prefix: Option<String>,
+    #[serde(default)]
+    pub constraint: Option<Constraint>,
     pub priority: DebconfPriority,
 }
 
-#[derive(Deserialize)]
+/// Extra validation applied on top of `VarType`'s own checks, enforced by
+/// an input-then-validate loop in the generated postinst script.
+#[derive(Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Constraint {
+    Range {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    Regex(String),
+}
+
+impl InternalVar {
+    /// Panics with the offending package/config/variable path if `default`
+    /// is set but isn't one of `Enum`'s `choices`, or if a `Range`
+    /// constraint is set on a type other than `Uint`/`BindPort`.
+    fn validate(&self, pkg: &str, conf_name: &str, var_name: &str) {
+        if let VarType::Enum { choices, .. } = &self.ty {
+            if let Some(default) = &self.default {
+                if !choices.iter().any(|choice| choice == default) {
+                    panic!(
+                        "Invalid spec {}: default {:?} of variable {}/{} is not among its choices {:?}",
+                        pkg, default, conf_name, var_name, choices,
+                    );
+                }
+            }
+        }
+
+        if let Some(Constraint::Range { .. }) = &self.constraint {
+            if !matches!(self.ty, VarType::Uint | VarType::BindPort) {
+                panic!(
+                    "Invalid spec {}: variable {}/{} has a Range constraint but its type isn't numeric",
+                    pkg, conf_name, var_name,
+                );
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum DebconfPriority {
     Low,
@@ -264,7 +369,7 @@ pub enum DebconfPriority {
     Dynamic { script: String },
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct ExternalVar {
     #[serde(default)]
     pub name: Option<String>,
@@ -272,7 +377,7 @@ pub struct ExternalVar {
     pub store: bool,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct HiddenVar {
     #[serde(flatten)]
     pub ty: VarType,
@@ -280,14 +385,14 @@ pub struct HiddenVar {
     pub val: HiddenVarVal,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum HiddenVarVal {
     Constant(String),
     Script(String),
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(tag = "type")]
 #[serde(rename_all = "snake_case")]
 pub enum VarType {
@@ -297,16 +402,17 @@ pub enum VarType {
     BindHost,
     BindPort,
     Path { file_type: Option<FileType>, create: Option<CreateFsObj>, },
+    Enum { choices: Vec<String>, #[serde(default)] multi: bool, },
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub enum FileType {
     Regular,
     Dir,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 pub struct CreateFsObj {
     // TODO: use better type
     pub mode: u16,
@@ -322,6 +428,38 @@ pub struct PackageInstance<'a> {
 }
 
 impl<'a> PackageInstance<'a> {
+    /// Returns this package's `config` deep-merged with every `ConfExt`
+    /// layer in `includes` that `extends` it, applied in ascending
+    /// `priority` order (last-layer-wins). Generators should consume this
+    /// instead of `config()` directly so overlays are transparent to them.
+    pub fn effective_config(&self) -> HashMap<String, Config> {
+        let mut merged = self.config().clone();
+
+        if let Some(includes) = self.includes {
+            let mut layers: Vec<(&str, &ConfExtPackageSpec)> = includes.iter()
+                .filter_map(|(pkg_name, package)| match &package.spec {
+                    PackageSpec::ConfExt(confext) if confext.extends.as_str() == self.name.as_ref() => Some((pkg_name.as_str(), confext)),
+                    _ => None,
+                })
+                .collect();
+            // `includes` is a HashMap, so break ties on equal priority by
+            // package name to keep merge order (and thus generated output)
+            // reproducible across runs instead of depending on hashing.
+            layers.sort_by_key(|(pkg_name, confext)| (confext.priority, *pkg_name));
+            let layers = layers.into_iter().map(|(_, confext)| confext);
+
+            for layer in layers {
+                for (conf_name, conf) in &layer.config {
+                    merged.entry(conf_name.clone())
+                        .and_modify(|existing| existing.merge_from(conf))
+                        .or_insert_with(|| conf.clone());
+                }
+            }
+        }
+
+        merged
+    }
+
     pub fn as_service<'b>(&'b self) -> Option<ServiceInstance<'b>> {
         if let PackageSpec::Service(service) = &self.spec {
             Some(ServiceInstance {
@@ -360,3 +498,159 @@ impl<'a> ServiceInstance<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dynamic_config(hvars: HashMap<String, HiddenVar>) -> Config {
+        Config {
+            public: false,
+            conf_type: ConfType::Dynamic {
+                format: ConfFormat::Json,
+                ivars: HashMap::new(),
+                evars: HashMap::new(),
+                hvars,
+                cat_dir: None,
+                cat_files: HashSet::new(),
+                comment: None,
+            },
+        }
+    }
+
+    fn hidden_var(value: &str) -> HiddenVar {
+        HiddenVar { ty: VarType::String, val: HiddenVarVal::Constant(value.to_owned()) }
+    }
+
+    #[test]
+    fn merge_from_merges_dynamic_fields_key_by_key() {
+        let mut base = dynamic_config(HashMap::from([("a".to_owned(), hidden_var("base-a"))]));
+        let layer = dynamic_config(HashMap::from([("b".to_owned(), hidden_var("layer-b"))]));
+
+        base.merge_from(&layer);
+
+        match &base.conf_type {
+            ConfType::Dynamic { hvars, .. } => {
+                assert_eq!(hvars.len(), 2);
+                assert!(hvars.contains_key("a"));
+                assert!(hvars.contains_key("b"));
+            },
+            _ => panic!("expected a Dynamic config"),
+        }
+    }
+
+    #[test]
+    fn merge_from_lets_later_layer_win_on_shared_key() {
+        let mut base = dynamic_config(HashMap::from([("a".to_owned(), hidden_var("base-a"))]));
+        let layer = dynamic_config(HashMap::from([("a".to_owned(), hidden_var("layer-a"))]));
+
+        base.merge_from(&layer);
+
+        match &base.conf_type {
+            ConfType::Dynamic { hvars, .. } => match &hvars["a"].val {
+                HiddenVarVal::Constant(value) => assert_eq!(value, "layer-a"),
+                _ => panic!("expected a Constant"),
+            },
+            _ => panic!("expected a Dynamic config"),
+        }
+    }
+
+    #[test]
+    fn merge_from_replaces_whole_entry_on_type_mismatch() {
+        let mut base = dynamic_config(HashMap::new());
+        let layer = Config { public: false, conf_type: ConfType::Static { content: "static".to_owned(), internal: false } };
+
+        base.merge_from(&layer);
+
+        assert!(matches!(base.conf_type, ConfType::Static { .. }));
+    }
+
+    fn service_package(name: &str, config: HashMap<String, Config>) -> Package {
+        Package {
+            name: name.to_owned(),
+            variants: HashSet::new(),
+            spec: PackageSpec::Service(ServicePackageSpec {
+                bin_package: name.to_owned(),
+                binary: name.to_owned(),
+                conf_param: None,
+                conf_d: None,
+                user: UserSpec { name: None, group: false, create: None },
+                config,
+                after: None,
+                extra_service_config: None,
+                summary: None,
+                long_doc: None,
+            }),
+        }
+    }
+
+    fn confext_package(name: &str, extends: &str, priority: i32, config: HashMap<String, Config>) -> Package {
+        Package {
+            name: name.to_owned(),
+            variants: HashSet::new(),
+            spec: PackageSpec::ConfExt(ConfExtPackageSpec {
+                extends: extends.to_owned(),
+                replaces: false,
+                summary: None,
+                long_doc: None,
+                config,
+                priority,
+            }),
+        }
+    }
+
+    #[test]
+    fn effective_config_applies_layers_in_ascending_priority_order() {
+        let base = service_package("base", HashMap::from([
+            ("app.conf".to_owned(), dynamic_config(HashMap::from([("v".to_owned(), hidden_var("base"))]))),
+        ]));
+        let includes = HashMap::from([
+            ("high".to_owned(), confext_package("high", "base", 10, HashMap::from([
+                ("app.conf".to_owned(), dynamic_config(HashMap::from([("v".to_owned(), hidden_var("high"))]))),
+            ]))),
+            ("low".to_owned(), confext_package("low", "base", 1, HashMap::from([
+                ("app.conf".to_owned(), dynamic_config(HashMap::from([("v".to_owned(), hidden_var("low"))]))),
+            ]))),
+        ]);
+
+        let instance = base.instantiate(None, Some(&includes)).unwrap();
+        let merged = instance.effective_config();
+
+        match &merged["app.conf"].conf_type {
+            ConfType::Dynamic { hvars, .. } => match &hvars["v"].val {
+                HiddenVarVal::Constant(value) => assert_eq!(value, "high"),
+                _ => panic!("expected a Constant"),
+            },
+            _ => panic!("expected a Dynamic config"),
+        }
+    }
+
+    #[test]
+    fn effective_config_breaks_priority_ties_by_package_name() {
+        let base = service_package("base", HashMap::from([
+            ("app.conf".to_owned(), dynamic_config(HashMap::new())),
+        ]));
+        let includes = HashMap::from([
+            ("zzz".to_owned(), confext_package("zzz", "base", 0, HashMap::from([
+                ("app.conf".to_owned(), dynamic_config(HashMap::from([("v".to_owned(), hidden_var("zzz"))]))),
+            ]))),
+            ("aaa".to_owned(), confext_package("aaa", "base", 0, HashMap::from([
+                ("app.conf".to_owned(), dynamic_config(HashMap::from([("v".to_owned(), hidden_var("aaa"))]))),
+            ]))),
+        ]);
+
+        let instance = base.instantiate(None, Some(&includes)).unwrap();
+        let merged = instance.effective_config();
+
+        // Equal priority ties break by package name ascending, so "zzz" is
+        // applied last and wins, regardless of the includes HashMap's
+        // iteration order.
+        match &merged["app.conf"].conf_type {
+            ConfType::Dynamic { hvars, .. } => match &hvars["v"].val {
+                HiddenVarVal::Constant(value) => assert_eq!(value, "zzz"),
+                _ => panic!("expected a Constant"),
+            },
+            _ => panic!("expected a Dynamic config"),
+        }
+    }
+}
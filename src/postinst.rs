@@ -0,0 +1,517 @@
+//! Helpers for generating the postinst shell snippets that assemble a
+//! `Dynamic` config file from debconf-sourced values.
+//!
+//! Most values are not known at generation time - they live in the debconf
+//! database and are only read back while `postinst` runs - so these
+//! functions emit shell code that interpolates a shell variable (assigned
+//! from `$RET` after a `db_get` earlier in the script) into the file,
+//! escaped for the target `ConfFormat`. Values already known at generation
+//! time (`LeafSource::Literal`) are escaped in Rust instead.
+
+use std::fmt::Write;
+use crate::{Constraint, DebconfPriority, FileType, InternalVar, VarType};
+
+/// Escapes a value for embedding inside a double-quoted JSON string.
+fn json_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes a value for embedding inside a double-quoted TOML basic string.
+fn toml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\u{7f}' => { let _ = write!(out, "\\u{:04x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Escapes a value for embedding inside a double-quoted YAML flow scalar.
+fn yaml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\x{:02x}", c as u32); },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Shell-quotes `value` so it is safe to paste as a single-quoted shell
+/// string literal inside a generated script.
+pub fn shell_single_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Shell pipeline that backslash/quote/control-character-escapes the value
+/// of `shell_var` for embedding inside a double-quoted JSON/TOML/YAML
+/// string. All lines are first joined into one pattern space, so every
+/// substitution sees the whole value. Backslashes are escaped first so the
+/// later substitutions don't double-escape the backslashes they themselves
+/// introduce.
+fn shell_escape_pipeline(shell_var: &str) -> String {
+    format!(
+        "$(printf '%s' \"${{{var}}}\" | sed -e ':a' -e '$!N' -e '$!ba' -e 's/\\\\/\\\\\\\\/g' -e 's/\"/\\\\\"/g' -e 's/\\r/\\\\r/g' -e 's/\\n/\\\\n/g' -e 's/\\t/\\\\t/g')",
+        var = shell_var,
+    )
+}
+
+/// A config-tree leaf as it will be rendered into the generated file: either
+/// a shell variable holding a debconf value read back at `postinst` run
+/// time, or a value already known at generation time (e.g. a
+/// `HiddenVarVal::Constant`), which is escaped once in Rust instead of being
+/// re-derived in shell on every run.
+#[derive(Clone)]
+pub enum LeafSource {
+    Dynamic(String),
+    Literal(String),
+}
+
+/// Printf argument for a quoted (`is_string`) leaf, already escaped for
+/// `escape` and shell-quoted so it can be pasted as one `printf` argument.
+fn quoted_arg(source: &LeafSource, escape: impl Fn(&str) -> String) -> String {
+    match source {
+        LeafSource::Dynamic(shell_var) => format!("\"{}\"", shell_escape_pipeline(shell_var)),
+        LeafSource::Literal(value) => shell_single_quote(&escape(value)),
+    }
+}
+
+/// Printf argument for a bare (non-string) leaf - emitted as-is, with no
+/// quoting or escaping, since `Bool`/`Uint`/`BindPort` values are already
+/// valid unquoted JSON/TOML/YAML scalars.
+fn bare_arg(source: &LeafSource) -> String {
+    match source {
+        LeafSource::Dynamic(shell_var) => format!("\"${{{}}}\"", shell_var),
+        LeafSource::Literal(value) => shell_single_quote(value),
+    }
+}
+
+/// Emits the shell line for a single config-file value with no key
+/// (`ConfFormat::Plain`, one value per line, whether the leaf sits in a
+/// table or in an array).
+pub fn emit_plain_leaf(out: &mut String, source: &LeafSource) {
+    match source {
+        LeafSource::Dynamic(shell_var) => { let _ = writeln!(out, "echo \"${{{var}}}\" >> \"$CONFIG_FILE\"", var = shell_var); },
+        LeafSource::Literal(value) => { let _ = writeln!(out, "printf '%s\\n' {value} >> \"$CONFIG_FILE\"", value = shell_single_quote(value)); },
+    }
+}
+
+/// Emits a `key = value` line for one TOML leaf.
+pub fn emit_toml_entry(out: &mut String, key: &str, source: &LeafSource, is_string: bool) {
+    let key_lit = shell_single_quote(key);
+    if is_string {
+        let _ = writeln!(out, "printf '%s = \"%s\"\\n' {key} {arg} >> \"$CONFIG_FILE\"", key = key_lit, arg = quoted_arg(source, toml_escape));
+    } else {
+        let _ = writeln!(out, "printf '%s = %s\\n' {key} {arg} >> \"$CONFIG_FILE\"", key = key_lit, arg = bare_arg(source));
+    }
+}
+
+/// Emits a `[path]`/`[[path]]` TOML table header.
+pub fn emit_toml_header(out: &mut String, path: &str, is_array_element: bool) {
+    let (open, close) = if is_array_element { ("[[", "]]") } else { ("[", "]") };
+    let _ = writeln!(out, "printf '{open}%s{close}\\n' {path} >> \"$CONFIG_FILE\"", open = open, close = close, path = shell_single_quote(path));
+}
+
+/// Emits a `key = [v1, v2, ...]` TOML inline array of scalars, as one
+/// `printf` call with one `%s` placeholder per item.
+pub fn emit_toml_inline_array(out: &mut String, key: &str, items: &[(LeafSource, bool)]) {
+    let mut format_str = String::new();
+    let mut args = Vec::new();
+    for (i, (source, is_string)) in items.iter().enumerate() {
+        if i > 0 {
+            format_str.push_str(", ");
+        }
+        if *is_string {
+            format_str.push_str("\"%s\"");
+            args.push(quoted_arg(source, toml_escape));
+        } else {
+            format_str.push_str("%s");
+            args.push(bare_arg(source));
+        }
+    }
+    let _ = writeln!(out, "printf '%s = [{items}]\\n' {key} {args} >> \"$CONFIG_FILE\"", items = format_str, key = shell_single_quote(key), args = args.join(" "));
+}
+
+/// Emits a `"key": value` JSON entry at `indent` levels of two-space
+/// indentation, with a trailing comma unless `last` is true.
+pub fn emit_json_entry(out: &mut String, indent: usize, key: &str, source: &LeafSource, is_string: bool, last: bool) {
+    let pad = "  ".repeat(indent);
+    let key_lit = shell_single_quote(key);
+    let comma = if last { "" } else { "," };
+    if is_string {
+        let _ = writeln!(out, "printf '{pad}\"%s\": \"%s\"{comma}\\n' {key} {arg} >> \"$CONFIG_FILE\"", pad = pad, key = key_lit, arg = quoted_arg(source, json_escape), comma = comma);
+    } else {
+        let _ = writeln!(out, "printf '{pad}\"%s\": %s{comma}\\n' {key} {arg} >> \"$CONFIG_FILE\"", pad = pad, key = key_lit, arg = bare_arg(source), comma = comma);
+    }
+}
+
+/// Emits a bare JSON array item (no key) at `indent` levels of indentation.
+pub fn emit_json_item(out: &mut String, indent: usize, source: &LeafSource, is_string: bool, last: bool) {
+    let pad = "  ".repeat(indent);
+    let comma = if last { "" } else { "," };
+    if is_string {
+        let _ = writeln!(out, "printf '{pad}\"%s\"{comma}\\n' {arg} >> \"$CONFIG_FILE\"", pad = pad, arg = quoted_arg(source, json_escape), comma = comma);
+    } else {
+        let _ = writeln!(out, "printf '{pad}%s{comma}\\n' {arg} >> \"$CONFIG_FILE\"", pad = pad, arg = bare_arg(source), comma = comma);
+    }
+}
+
+/// Emits the opening `{`/`[` of a JSON object/array, optionally prefixed
+/// with `"key": ` when it's a named entry rather than an array element.
+pub fn emit_json_open(out: &mut String, indent: usize, key: Option<&str>, is_array: bool) {
+    let pad = "  ".repeat(indent);
+    let bracket = if is_array { "[" } else { "{" };
+    match key {
+        Some(k) => {
+            let key_lit = shell_single_quote(k);
+            let _ = writeln!(out, "printf '{pad}\"%s\": {bracket}\\n' {key} >> \"$CONFIG_FILE\"", pad = pad, bracket = bracket, key = key_lit);
+        },
+        None => {
+            let _ = writeln!(out, "printf '{pad}{bracket}\\n' >> \"$CONFIG_FILE\"", pad = pad, bracket = bracket);
+        },
+    }
+}
+
+/// Emits the closing `}`/`]` of a JSON object/array, with a trailing comma
+/// unless `last` is true.
+pub fn emit_json_close(out: &mut String, indent: usize, is_array: bool, last: bool) {
+    let pad = "  ".repeat(indent);
+    let bracket = if is_array { "]" } else { "}" };
+    let comma = if last { "" } else { "," };
+    let _ = writeln!(out, "printf '{pad}{bracket}{comma}\\n' >> \"$CONFIG_FILE\"", pad = pad, bracket = bracket, comma = comma);
+}
+
+/// Emits a `key: value` YAML mapping entry, handed an already-computed
+/// `pad` (the leading text of the line - plain indentation, or indentation
+/// followed by `- ` for the first key of a sequence-of-mappings item).
+/// Multi-line string values are written as a block scalar instead of a
+/// flow string.
+pub fn emit_yaml_entry(out: &mut String, pad: &str, key: &str, source: &LeafSource, is_string: bool) {
+    let key_lit = shell_single_quote(key);
+    if !is_string {
+        let _ = writeln!(out, "printf '{pad}%s: %s\\n' {key} {arg} >> \"$CONFIG_FILE\"", pad = pad, key = key_lit, arg = bare_arg(source));
+        return;
+    }
+    // The block scalar's body lines are indented relative to the
+    // *key's* column, not prefixed with `- ` again on every line, so
+    // a `- ` lead-in (a sequence-of-mappings' first key) is replaced
+    // by equal-width spaces before it's reused as the body prefix.
+    let body_pad = match pad.strip_suffix("- ") {
+        Some(rest) => format!("{}  ", rest),
+        None => pad.to_owned(),
+    };
+    match source {
+        LeafSource::Dynamic(shell_var) => {
+            // A literal newline as the `case` pattern (valid POSIX sh) is the
+            // portable way to test "does this value contain a newline"
+            // without relying on bash's `$'...'` quoting.
+            let _ = writeln!(out, "case \"${{{var}}}\" in", var = shell_var);
+            let _ = write!(out, "*\"\n\"*)\n");
+            let _ = writeln!(out, "    printf '{pad}%s: |\\n' {key} >> \"$CONFIG_FILE\"", pad = pad, key = key_lit);
+            let _ = writeln!(out, "    printf '%s\\n' \"${{{var}}}\" | sed 's/^/{body_pad}    /' >> \"$CONFIG_FILE\"", var = shell_var, body_pad = body_pad);
+            let _ = writeln!(out, "    ;;");
+            let _ = writeln!(out, "*)");
+            let _ = writeln!(out, "    printf '{pad}%s: \"%s\"\\n' {key} \"{escaped}\" >> \"$CONFIG_FILE\"", pad = pad, key = key_lit, escaped = shell_escape_pipeline(shell_var));
+            let _ = writeln!(out, "    ;;");
+            let _ = writeln!(out, "esac");
+        },
+        LeafSource::Literal(value) if value.contains('\n') => {
+            let _ = writeln!(out, "printf '{pad}%s: |\\n' {key} >> \"$CONFIG_FILE\"", pad = pad, key = key_lit);
+            for line in value.lines() {
+                let _ = writeln!(out, "printf '%s\\n' {line} >> \"$CONFIG_FILE\"", line = shell_single_quote(&format!("{}    {}", body_pad, line)));
+            }
+        },
+        LeafSource::Literal(value) => {
+            let _ = writeln!(out, "printf '{pad}%s: \"%s\"\\n' {key} {arg} >> \"$CONFIG_FILE\"", pad = pad, key = key_lit, arg = shell_single_quote(&yaml_escape(value)));
+        },
+    }
+}
+
+/// Emits the `key:` header line that introduces a nested YAML mapping or
+/// sequence, using the same already-computed `pad` convention as
+/// `emit_yaml_entry`.
+pub fn emit_yaml_header(out: &mut String, pad: &str, key: &str) {
+    let _ = writeln!(out, "printf '{pad}%s:\\n' {key} >> \"$CONFIG_FILE\"", pad = pad, key = shell_single_quote(key));
+}
+
+/// Emits one bare YAML sequence item (no key), using the same
+/// already-computed `pad` convention as `emit_yaml_entry` (indentation
+/// followed by `- `).
+pub fn emit_yaml_item(out: &mut String, pad: &str, source: &LeafSource, is_string: bool) {
+    if is_string {
+        let _ = writeln!(out, "printf '{pad}\"%s\"\\n' {arg} >> \"$CONFIG_FILE\"", pad = pad, arg = quoted_arg(source, yaml_escape));
+    } else {
+        let _ = writeln!(out, "printf '{pad}%s\\n' {arg} >> \"$CONFIG_FILE\"", pad = pad, arg = bare_arg(source));
+    }
+}
+
+/// Emits the shell snippet that computes the preseed default for `template`
+/// (a `pkg/var` debconf template name) and feeds it into `db_set` before the
+/// prompt is shown. If `var.default_from_env` is set, the named environment
+/// variable (joined with `var.env_prefix`, e.g. `MYPKG_` + `PORT`) is
+/// preferred when present; the static `var.default`, if any, is the
+/// fallback.
+pub fn emit_default(out: &mut String, template: &str, var: &InternalVar) {
+    match &var.default_from_env {
+        Some(env_name) => {
+            let full_name = match &var.env_prefix {
+                Some(prefix) => format!("{}{}", prefix, env_name),
+                None => env_name.clone(),
+            };
+            let _ = writeln!(out, "if [ -n \"${{{env}+x}}\" ]; then", env = full_name);
+            let _ = writeln!(out, "  db_set {} \"${{{env}}}\"", template, env = full_name);
+            if let Some(default) = &var.default {
+                let _ = writeln!(out, "else");
+                let _ = writeln!(out, "  db_get {}", template);
+                let _ = writeln!(out, "  if [ -z \"$RET\" ]; then");
+                let _ = writeln!(out, "    db_set {} {}", template, shell_single_quote(default));
+                let _ = writeln!(out, "  fi");
+                let _ = writeln!(out, "fi");
+            } else {
+                let _ = writeln!(out, "fi");
+            }
+        },
+        None => {
+            if let Some(default) = &var.default {
+                let _ = writeln!(out, "db_set {} {}", template, shell_single_quote(default));
+            }
+        },
+    }
+}
+
+/// Renders a `DebconfPriority` into the shell expression `db_input` expects:
+/// a literal word for the static priorities, or a `$(...)` command
+/// substitution running the configured script for `Dynamic`.
+pub fn priority_str(priority: &DebconfPriority) -> String {
+    match priority {
+        DebconfPriority::Low => "low".to_owned(),
+        DebconfPriority::Medium => "medium".to_owned(),
+        DebconfPriority::High => "high".to_owned(),
+        DebconfPriority::Critical => "critical".to_owned(),
+        DebconfPriority::Dynamic { script } => format!("\"$({})\"", script),
+    }
+}
+
+/// Maximum number of debconf input/validate attempts `emit_validated_input`
+/// makes before giving up. Bounds the loop so a non-interactive frontend
+/// stuck re-offering the same invalid value can't hang `postinst` forever.
+const MAX_VALIDATION_ATTEMPTS: u32 = 10;
+
+/// Emits a debconf input-then-validate loop for `template`: prompt, read the
+/// value back into `$value`, check it against the constraints implied by
+/// `ty` (refined by an optional explicit `constraint`), and on failure mark
+/// the template unseen with `db_fset ... seen false` and prompt again.
+/// Aborts with an error after `MAX_VALIDATION_ATTEMPTS` failed attempts
+/// instead of retrying forever against a non-interactive frontend.
+pub fn emit_validated_input(out: &mut String, priority: &str, template: &str, ty: &VarType, constraint: Option<&Constraint>) {
+    let _ = writeln!(out, "attempts=0");
+    let _ = writeln!(out, "while :; do");
+    let _ = writeln!(out, "  db_input {} {} || true", priority, template);
+    let _ = writeln!(out, "  db_go || true");
+    let _ = writeln!(out, "  db_get {}", template);
+    let _ = writeln!(out, "  value=\"$RET\"");
+    let _ = writeln!(out, "  if {}; then", validation_condition(ty, constraint));
+    let _ = writeln!(out, "    attempts=$((attempts + 1))");
+    let _ = writeln!(out, "    if [ \"$attempts\" -ge {} ]; then", MAX_VALIDATION_ATTEMPTS);
+    let _ = writeln!(out, "      echo \"{}: no valid value after {} attempts\" >&2", template, MAX_VALIDATION_ATTEMPTS);
+    let _ = writeln!(out, "      exit 1");
+    let _ = writeln!(out, "    fi");
+    let _ = writeln!(out, "    db_fset {} seen false", template);
+    let _ = writeln!(out, "    continue");
+    let _ = writeln!(out, "  fi");
+    let _ = writeln!(out, "  break");
+    let _ = writeln!(out, "done");
+}
+
+/// Builds the POSIX ERE that a valid IPv4 address or hostname must match.
+fn bind_host_regex() -> String {
+    let octet = r"(25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])";
+    let ipv4 = format!(r"({o}\.){{3}}{o}", o = octet);
+    let label = r"[A-Za-z0-9]([A-Za-z0-9-]{0,61}[A-Za-z0-9])?";
+    let hostname = format!(r"{l}(\.{l})*", l = label);
+    format!("^({}|{})$", ipv4, hostname)
+}
+
+/// Builds the shell boolean expression that is *true* when `$value` fails
+/// validation for `ty`/`constraint`.
+fn validation_condition(ty: &VarType, constraint: Option<&Constraint>) -> String {
+    let base = match ty {
+        VarType::BindPort => "! printf '%s' \"$value\" | grep -Eq '^[0-9]+$' || [ \"$value\" -lt 1 ] || [ \"$value\" -gt 65535 ]".to_owned(),
+        VarType::Uint => "! printf '%s' \"$value\" | grep -Eq '^[0-9]+$'".to_owned(),
+        VarType::BindHost => format!("! printf '%s' \"$value\" | grep -Eq {}", shell_single_quote(&bind_host_regex())),
+        VarType::Path { file_type: Some(FileType::Regular), .. } => "[ -n \"$value\" ] && [ ! -f \"$value\" ]".to_owned(),
+        VarType::Path { file_type: Some(FileType::Dir), .. } => "[ -n \"$value\" ] && [ ! -d \"$value\" ]".to_owned(),
+        _ => "false".to_owned(),
+    };
+
+    match constraint {
+        // `InternalVar::validate` rejects `Range` on non-numeric types at
+        // spec-load time, so `base` is already a numeric check here.
+        Some(Constraint::Range { min, max }) => {
+            let mut cond = base;
+            if let Some(min) = min {
+                cond = format!("{} || [ \"$value\" -lt {} ]", cond, min);
+            }
+            if let Some(max) = max {
+                cond = format!("{} || [ \"$value\" -gt {} ]", cond, max);
+            }
+            cond
+        },
+        Some(Constraint::Regex(pattern)) => format!("{} || ! printf '%s' \"$value\" | grep -Eq {}", base, shell_single_quote(pattern)),
+        None => base,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn internal_var(default: Option<&str>, default_from_env: Option<&str>, env_prefix: Option<&str>) -> InternalVar {
+        InternalVar {
+            ty: VarType::String,
+            summary: "summary".to_owned(),
+            long_doc: None,
+            default: default.map(str::to_owned),
+            default_from_env: default_from_env.map(str::to_owned),
+            env_prefix: env_prefix.map(str::to_owned),
+            constraint: None,
+            priority: DebconfPriority::Medium,
+        }
+    }
+
+    #[test]
+    fn emit_default_sets_the_static_default_when_no_env_var_is_configured() {
+        let mut out = String::new();
+        emit_default(&mut out, "pkg/var", &internal_var(Some("fallback"), None, None));
+        assert_eq!(out, "db_set pkg/var 'fallback'\n");
+    }
+
+    #[test]
+    fn emit_default_emits_nothing_with_no_default_and_no_env_var() {
+        let mut out = String::new();
+        emit_default(&mut out, "pkg/var", &internal_var(None, None, None));
+        assert_eq!(out, "");
+    }
+
+    #[test]
+    fn emit_default_prefers_the_env_var_when_set_and_falls_back_to_the_static_default() {
+        let mut out = String::new();
+        emit_default(&mut out, "pkg/var", &internal_var(Some("fallback"), Some("PORT"), Some("MYPKG_")));
+        assert_eq!(out, concat!(
+            "if [ -n \"${MYPKG_PORT+x}\" ]; then\n",
+            "  db_set pkg/var \"${MYPKG_PORT}\"\n",
+            "else\n",
+            "  db_get pkg/var\n",
+            "  if [ -z \"$RET\" ]; then\n",
+            "    db_set pkg/var 'fallback'\n",
+            "  fi\n",
+            "fi\n",
+        ));
+    }
+
+    #[test]
+    fn emit_default_env_branch_without_a_static_default_only_seeds_from_env() {
+        let mut out = String::new();
+        emit_default(&mut out, "pkg/var", &internal_var(None, Some("PORT"), None));
+        assert_eq!(out, concat!(
+            "if [ -n \"${PORT+x}\" ]; then\n",
+            "  db_set pkg/var \"${PORT}\"\n",
+            "fi\n",
+        ));
+    }
+
+    #[test]
+    fn validation_condition_rejects_non_numeric_uint_values() {
+        assert_eq!(validation_condition(&VarType::Uint, None), "! printf '%s' \"$value\" | grep -Eq '^[0-9]+$'");
+    }
+
+    #[test]
+    fn validation_condition_rejects_out_of_range_bind_ports() {
+        let cond = validation_condition(&VarType::BindPort, None);
+        assert!(cond.contains("-lt 1"));
+        assert!(cond.contains("-gt 65535"));
+    }
+
+    #[test]
+    fn validation_condition_accepts_anything_with_no_type_specific_check_and_no_constraint() {
+        assert_eq!(validation_condition(&VarType::Bool, None), "false");
+    }
+
+    #[test]
+    fn validation_condition_range_constraint_adds_bounds_on_top_of_the_base_check() {
+        let cond = validation_condition(&VarType::Uint, Some(&Constraint::Range { min: Some(1), max: Some(10) }));
+        assert!(cond.contains("-lt 1"));
+        assert!(cond.contains("-gt 10"));
+    }
+
+    #[test]
+    fn validation_condition_range_constraint_with_only_a_min_omits_the_max_check() {
+        let cond = validation_condition(&VarType::Uint, Some(&Constraint::Range { min: Some(1), max: None }));
+        assert!(cond.contains("-lt 1"));
+        assert!(!cond.contains("-gt"));
+    }
+
+    #[test]
+    fn validation_condition_regex_constraint_is_ored_onto_the_base_check() {
+        let cond = validation_condition(&VarType::Bool, Some(&Constraint::Regex("^foo$".to_owned())));
+        assert_eq!(cond, "false || ! printf '%s' \"$value\" | grep -Eq '^foo$'");
+    }
+
+    #[test]
+    fn json_escape_handles_quotes_backslashes_and_control_chars() {
+        assert_eq!(json_escape("a\"b\\c\nd\re\tf\x01g"), "a\\\"b\\\\c\\nd\\re\\tf\\u0001g");
+    }
+
+    #[test]
+    fn toml_escape_handles_quotes_backslashes_and_whitespace() {
+        assert_eq!(toml_escape("a\"b\\c\nd\re\tf"), "a\\\"b\\\\c\\nd\\re\\tf");
+    }
+
+    #[test]
+    fn yaml_escape_handles_quotes_backslashes_and_control_chars() {
+        assert_eq!(yaml_escape("a\"b\\c\nd\re\tf\x01g"), "a\\\"b\\\\c\\nd\\re\\tf\\x01g");
+    }
+
+    #[test]
+    fn shell_single_quote_escapes_embedded_single_quotes() {
+        assert_eq!(shell_single_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn shell_escape_pipeline_escapes_backslash_before_other_chars() {
+        // The backslash substitution must run first, or later substitutions
+        // (e.g. the one introducing `\r`/`\n`/`\t`) would have their own
+        // backslashes doubled right back up.
+        let pipeline = shell_escape_pipeline("VALUE");
+        let backslash_pos = pipeline.find(r"s/\\/\\\\/g").unwrap();
+        let cr_pos = pipeline.find(r"s/\r/\\r/g").unwrap();
+        let newline_pos = pipeline.find(r"s/\n/\\n/g").unwrap();
+        assert!(backslash_pos < cr_pos);
+        assert!(cr_pos < newline_pos);
+    }
+}